@@ -3,6 +3,8 @@ use std::path::PathBuf;
 use clap::{Parser, Subcommand};
 use url::Url;
 
+use crate::grades::PenaltyPolicy;
+
 #[derive(Parser)]
 pub struct Cli {
   #[arg(short, long, env = "MOODLE_BASE_URL")]
@@ -14,6 +16,29 @@ pub struct Cli {
   #[arg(short, long, env = "MOODLE_PASSWORD")]
   pub password: String,
 
+  #[arg(
+    long,
+    global = true,
+    help = "Skip the cached token and always re-authenticate"
+  )]
+  pub no_cache: bool,
+
+  #[arg(
+    long,
+    global = true,
+    default_value_t = 3,
+    help = "Maximum number of retries for transient web-service errors"
+  )]
+  pub max_retries: u32,
+
+  #[arg(
+    long,
+    global = true,
+    default_value_t = 500,
+    help = "Base retry delay in milliseconds (doubles on every attempt)"
+  )]
+  pub retry_delay: u64,
+
   #[command(subcommand)]
   pub command: Commands,
 }
@@ -38,10 +63,66 @@ pub enum Commands {
       help = "Output file [default: assignment_{assignment_id}.yml]"
     )]
     output_file: Option<PathBuf>,
+
+    #[arg(
+      short,
+      long,
+      default_value_t = 8,
+      help = "Number of files to download in parallel"
+    )]
+    jobs: usize,
   },
 
   UploadGrades {
     #[arg(short, long, help = "Assignment file")]
     file: PathBuf,
+
+    #[arg(long, help = "Preview grade changes without uploading them")]
+    dry_run: bool,
+
+    #[arg(long, help = "Ask for confirmation before uploading grades")]
+    confirm: bool,
+
+    #[arg(
+      long,
+      help = "Late-penalty spec, overriding the file's `penalty` entry \
+              (percent[:period_seconds[:grace_seconds[:floor]]])"
+    )]
+    penalty: Option<PenaltyPolicy>,
+  },
+
+  #[command(about = "Clear the cached token for the given user")]
+  Logout,
+
+  Watch {
+    #[arg(short, long, help = "Course id")]
+    course_id: u64,
+
+    #[arg(short, long, help = "Assignment id")]
+    assignment_id: u64,
+
+    #[arg(short, long, default_value_t = 60, help = "Polling interval in seconds")]
+    interval: u64,
+
+    #[arg(
+      short,
+      long,
+      help = "Output directory [default: assignment_{assignment_id}]"
+    )]
+    output_dir: Option<PathBuf>,
+
+    #[arg(
+      short,
+      long,
+      help = "Log file [default: assignment_{assignment_id}_watch.yml]"
+    )]
+    log_file: Option<PathBuf>,
+
+    #[arg(
+      long,
+      default_value_t = 5,
+      help = "Consecutive poll failures before giving up"
+    )]
+    max_failures: u32,
   },
 }
@@ -0,0 +1,170 @@
+use std::{fs, path::Path, str::FromStr};
+
+use serde::Deserialize;
+
+use crate::moodle::{MAssignment, MSubmission, Result};
+
+#[derive(Deserialize)]
+pub struct AssignmentFile {
+  pub course_id: u64,
+  pub assignment_id: u64,
+
+  #[serde(default)]
+  pub penalty: Option<PenaltyPolicy>,
+
+  pub grades: Vec<GradeEntry>,
+}
+
+#[derive(Deserialize)]
+pub struct GradeEntry {
+  pub user_id: u64,
+  pub grade: f32,
+  pub feedback: Option<String>,
+
+  #[serde(default)]
+  pub waive_penalty: bool,
+}
+
+impl AssignmentFile {
+  pub fn load(path: impl AsRef<Path>) -> Result<AssignmentFile> {
+    Ok(serde_yaml::from_str(&fs::read_to_string(path)?)?)
+  }
+}
+
+/// A late-penalty deduction rule: `percent_per_period`% is deducted for
+/// every started `period_seconds` a submission is late, after an optional
+/// `grace_seconds` window, never reducing the grade below `floor`.
+#[derive(Clone, Deserialize)]
+pub struct PenaltyPolicy {
+  pub percent_per_period: f32,
+
+  #[serde(default = "PenaltyPolicy::default_period_seconds")]
+  pub period_seconds: u64,
+
+  #[serde(default)]
+  pub grace_seconds: u64,
+
+  #[serde(default)]
+  pub floor: f32,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("invalid penalty spec {0:?} (expected percent[:period_seconds[:grace_seconds[:floor]]])")]
+pub struct ParsePenaltyError(String);
+
+impl PenaltyPolicy {
+  const fn default_period_seconds() -> u64 {
+    86400
+  }
+
+  /// Deduct late penalties from `grade`, returning the penalized grade
+  /// (clamped to `[floor, max_grade]`) and a human-readable breakdown, or
+  /// `(grade, None)` if the submission is within the grace window.
+  pub fn apply(
+    &self,
+    grade: f32,
+    max_grade: f32,
+    late_seconds: u64,
+  ) -> (f32, Option<String>) {
+    let chargeable = late_seconds.saturating_sub(self.grace_seconds);
+    if chargeable == 0 {
+      return (grade, None);
+    }
+
+    let periods = chargeable.div_ceil(self.period_seconds.max(1));
+    #[allow(clippy::cast_precision_loss)]
+    let deduction_percent =
+      (self.percent_per_period * periods as f32).min(100.0);
+    let floor = self.floor.min(max_grade);
+    let penalized =
+      (grade - grade * deduction_percent / 100.0).clamp(floor, max_grade);
+
+    let note = format!(
+      "Late penalty: -{deduction_percent:.0}% ({periods} period(s) late) -> \
+       {penalized:.2}/{max_grade:.2}"
+    );
+
+    (penalized, Some(note))
+  }
+}
+
+impl FromStr for PenaltyPolicy {
+  type Err = ParsePenaltyError;
+
+  fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+    let invalid = || ParsePenaltyError(s.to_owned());
+    let mut fields = s.split(':');
+
+    let percent_per_period =
+      fields.next().ok_or_else(invalid)?.parse().map_err(|_| invalid())?;
+    let period_seconds = fields
+      .next()
+      .map(str::parse)
+      .transpose()
+      .map_err(|_| invalid())?
+      .unwrap_or_else(Self::default_period_seconds);
+    let grace_seconds = fields
+      .next()
+      .map(str::parse)
+      .transpose()
+      .map_err(|_| invalid())?
+      .unwrap_or(0);
+    let floor = fields
+      .next()
+      .map(str::parse)
+      .transpose()
+      .map_err(|_| invalid())?
+      .unwrap_or(0.0);
+
+    Ok(PenaltyPolicy {
+      percent_per_period,
+      period_seconds,
+      grace_seconds,
+      floor,
+    })
+  }
+}
+
+/// Resolve the grade that should actually be uploaded for `entry`, applying
+/// `policy` (unless the entry waives it or no submission is on record) and
+/// returning the penalty breakdown to annotate the feedback with.
+pub fn resolve_grade(
+  entry: &GradeEntry,
+  assignment: &MAssignment,
+  submissions: &[MSubmission],
+  policy: Option<&PenaltyPolicy>,
+) -> (f32, Option<String>) {
+  let Some(policy) = policy.filter(|_| !entry.waive_penalty) else {
+    return (entry.grade, None);
+  };
+
+  let Some(submission) =
+    submissions.iter().find(|submission| submission.userid == entry.user_id)
+  else {
+    return (entry.grade, None);
+  };
+
+  let late_seconds = assignment.calculate_late(submission);
+  policy.apply(entry.grade, assignment.max_grade, late_seconds)
+}
+
+/// Combine the user-supplied feedback with a penalty breakdown, if any.
+pub fn combined_feedback(
+  entry: &GradeEntry,
+  penalty_note: Option<&str>,
+) -> Option<String> {
+  match (entry.feedback.as_deref(), penalty_note) {
+    (None, None) => None,
+    (Some(feedback), None) => Some(feedback.to_owned()),
+    (None, Some(note)) => Some(note.to_owned()),
+    (Some(feedback), Some(note)) => Some(format!("{feedback}\n\n{note}")),
+  }
+}
+
+/// Whether `UploadGrades` should actually save grades or just preview the
+/// changes it would make.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+  Commit,
+  DryRun,
+}
@@ -0,0 +1,281 @@
+mod cli;
+mod grades;
+mod moodle;
+mod retry;
+mod token_cache;
+mod watch;
+
+use std::{
+  fs,
+  io::{self, Write},
+  time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use clap::Parser;
+use serde::Serialize;
+use tabled::{settings::Style, Table, Tabled};
+
+use cli::{Cli, Commands};
+use grades::{AssignmentFile, Mode};
+use moodle::Moodle;
+use retry::RetryPolicy;
+
+fn main() -> anyhow::Result<()> {
+  let cli = Cli::parse();
+
+  if let Commands::Logout = cli.command {
+    Moodle::clear_token(&cli.base_url, &cli.username)?;
+    println!("Cleared cached token for {}", cli.username);
+    return Ok(());
+  }
+
+  let retry = RetryPolicy {
+    max_retries: cli.max_retries,
+    base_delay: Duration::from_millis(cli.retry_delay),
+  };
+
+  let moodle = Moodle::connect(
+    &cli.base_url,
+    &cli.username,
+    &cli.password,
+    !cli.no_cache,
+    retry,
+  )?;
+
+  match cli.command {
+    Commands::ListAssignments { course_id } => {
+      print!("{}", moodle.get_course_assignments(course_id)?);
+    }
+
+    Commands::DownloadSubmissions {
+      course_id,
+      assignment_id,
+      output_file,
+      jobs,
+    } => {
+      let course = moodle.get_course_assignments(course_id)?;
+      course.get_assignment(assignment_id)?;
+
+      let output_file = output_file
+        .unwrap_or_else(|| format!("assignment_{assignment_id}.yml").into());
+
+      let submissions = moodle.get_submissions(assignment_id)?;
+
+      let mut jobs_list = Vec::new();
+      let mut submission_users = Vec::new();
+      for submission in &submissions {
+        let user = moodle.get_user(submission.userid)?;
+        let user_dir = output_file
+          .parent()
+          .unwrap_or_else(|| std::path::Path::new("."))
+          .join(format!("{assignment_id}_{}", user.id));
+        fs::create_dir_all(&user_dir)?;
+
+        let start = jobs_list.len();
+        for file in &submission.files {
+          let path = user_dir.join(file.fullpath());
+          if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+          }
+          jobs_list.push((file.clone(), path));
+        }
+
+        submission_users.push((user, start, jobs_list.len()));
+      }
+
+      let results = moodle.download_submissions(&jobs_list, jobs);
+
+      let mut manifest = Vec::new();
+      for (submission, (user, start, end)) in
+        submissions.iter().zip(submission_users)
+      {
+        let mut files = Vec::new();
+        let mut errors = Vec::new();
+
+        for idx in start..end {
+          let (file, path) = &jobs_list[idx];
+          match &results[idx] {
+            Ok(()) => files.push(path.display().to_string()),
+            Err(err) => {
+              let message = format!("{} :: {err}", file.fullpath().display());
+              eprintln!("Failed to download {message}");
+              errors.push(message);
+            }
+          }
+        }
+
+        manifest.push(ManifestEntry {
+          user_id: submission.userid,
+          fullname: user.fullname,
+          timemodified: submission.timemodified,
+          files,
+          errors,
+        });
+      }
+
+      fs::write(&output_file, serde_yaml::to_string(&manifest)?)?;
+    }
+
+    Commands::UploadGrades {
+      file,
+      dry_run,
+      confirm,
+      penalty,
+    } => {
+      let assignment_file = AssignmentFile::load(file)?;
+      let course = moodle.get_course_assignments(assignment_file.course_id)?;
+      let assignment = course.get_assignment(assignment_file.assignment_id)?;
+      let submissions =
+        moodle.get_submissions(assignment_file.assignment_id)?;
+      let policy = penalty.as_ref().or(assignment_file.penalty.as_ref());
+      let mode = if dry_run { Mode::DryRun } else { Mode::Commit };
+
+      if dry_run || confirm {
+        let old_grades =
+          moodle.get_grades(assignment_file.assignment_id)?;
+
+        let mut diffs = Vec::new();
+        for entry in &assignment_file.grades {
+          let user = moodle.get_user(entry.user_id)?;
+          let old_grade = old_grades.get(&entry.user_id).copied();
+          let old_feedback = moodle
+            .get_feedback(assignment_file.assignment_id, entry.user_id)?;
+          let (resolved_grade, penalty_note) =
+            grades::resolve_grade(entry, assignment, &submissions, policy);
+          let clamped = resolved_grade.max(0.0).min(assignment.max_grade);
+          let feedback =
+            grades::combined_feedback(entry, penalty_note.as_deref());
+          let feedback_changed = feedback.as_deref().unwrap_or_default().trim()
+            != old_feedback.trim();
+
+          diffs.push(GradeDiff {
+            user: user.fullname,
+            old_grade: old_grade
+              .map_or_else(|| "-".to_owned(), |grade| grade.to_string()),
+            new_grade: resolved_grade.to_string(),
+            clamped_grade: clamped.to_string(),
+            feedback_changed: if feedback_changed { "yes" } else { "no" }
+              .to_owned(),
+          });
+        }
+
+        println!("{}", Table::new(&diffs).with(Style::rounded()));
+      }
+
+      if mode == Mode::DryRun {
+        return Ok(());
+      }
+
+      if confirm && !prompt_confirm("Upload these grades?")? {
+        println!("Aborted.");
+        return Ok(());
+      }
+
+      let mut rows = Vec::new();
+      for entry in &assignment_file.grades {
+        let user = moodle.get_user(entry.user_id)?;
+        let (resolved_grade, penalty_note) =
+          grades::resolve_grade(entry, assignment, &submissions, policy);
+        let feedback =
+          grades::combined_feedback(entry, penalty_note.as_deref());
+        let status = match moodle.upload_grade(
+          assignment,
+          &user,
+          resolved_grade,
+          feedback.as_deref(),
+        ) {
+          Ok(()) => "uploaded".to_owned(),
+          Err(err) => format!("failed: {err}"),
+        };
+
+        rows.push(UploadRow {
+          user_id: entry.user_id,
+          grade: resolved_grade,
+          status,
+        });
+      }
+
+      println!("{}", Table::new(&rows).with(Style::rounded()));
+    }
+
+    Commands::Watch {
+      course_id,
+      assignment_id,
+      interval,
+      output_dir,
+      log_file,
+      max_failures,
+    } => {
+      let course = moodle.get_course_assignments(course_id)?;
+      course.get_assignment(assignment_id)?;
+
+      let output_dir = output_dir
+        .unwrap_or_else(|| format!("assignment_{assignment_id}").into());
+      let log_file = log_file
+        .unwrap_or_else(|| format!("assignment_{assignment_id}_watch.yml").into());
+
+      watch::run(
+        &moodle,
+        assignment_id,
+        &output_dir,
+        &log_file,
+        Duration::from_secs(interval),
+        max_failures,
+      )?;
+    }
+
+    Commands::Logout => unreachable!("handled above"),
+  }
+
+  Ok(())
+}
+
+#[derive(Serialize)]
+struct ManifestEntry {
+  user_id: u64,
+  fullname: String,
+  timemodified: DateTime<Utc>,
+  files: Vec<String>,
+  errors: Vec<String>,
+}
+
+#[derive(Tabled)]
+struct UploadRow {
+  #[tabled(rename = "User")]
+  user_id: u64,
+
+  #[tabled(rename = "Grade")]
+  grade: f32,
+
+  #[tabled(rename = "Status")]
+  status: String,
+}
+
+#[derive(Tabled)]
+struct GradeDiff {
+  #[tabled(rename = "User")]
+  user: String,
+
+  #[tabled(rename = "Old Grade")]
+  old_grade: String,
+
+  #[tabled(rename = "New Grade")]
+  new_grade: String,
+
+  #[tabled(rename = "Clamped Grade")]
+  clamped_grade: String,
+
+  #[tabled(rename = "Feedback Changed")]
+  feedback_changed: String,
+}
+
+fn prompt_confirm(question: &str) -> anyhow::Result<bool> {
+  print!("{question} [y/N] ");
+  io::stdout().flush()?;
+
+  let mut answer = String::new();
+  io::stdin().read_line(&mut answer)?;
+
+  Ok(answer.trim().eq_ignore_ascii_case("y"))
+}
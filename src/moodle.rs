@@ -1,21 +1,29 @@
 use std::{
   collections::HashMap,
   fmt,
-  fs::File,
+  fs::{self, File},
   io,
   path::{Path, PathBuf},
   result,
+  sync::{
+    atomic::{AtomicUsize, Ordering},
+    Mutex,
+  },
 };
 
 use chrono::{serde::ts_seconds, DateTime, Utc};
-use reqwest::blocking::Client;
+use reqwest::{blocking::Client, header::RANGE, StatusCode};
 use serde::{de, Deserialize, Deserializer, Serialize};
 use tabled::{settings::Style, Table, Tabled};
 use url::Url;
 
+use crate::{retry::RetryPolicy, token_cache::TokenCache};
+
 pub struct Moodle {
   url: Url,
   token: String,
+  client: Client,
+  retry: RetryPolicy,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -24,6 +32,7 @@ pub enum Error {
   Reqwest(#[from] reqwest::Error),
   Parse(#[from] url::ParseError),
   JsonDeserialize(#[from] serde_json::Error),
+  Yaml(#[from] serde_yaml::Error),
   IO(#[from] io::Error),
 
   #[error("Login Error :: {0}")]
@@ -31,6 +40,12 @@ pub enum Error {
 
   #[error("{0:?} (id: {1}) not found!")]
   NotFound(NotFound, u64),
+
+  #[error("Moodle Exception :: {errorcode} :: {message}")]
+  Exception { errorcode: String, message: String },
+
+  #[error("HTTP {status} response from Moodle")]
+  Status { status: StatusCode },
 }
 
 #[derive(Debug)]
@@ -53,10 +68,76 @@ struct LoginResponse {
   error: String,
 }
 
+#[derive(Deserialize)]
+struct SiteInfo {
+  #[allow(dead_code)]
+  userid: u64,
+}
+
+#[derive(Deserialize)]
+struct MoodleException {
+  errorcode: String,
+  message: String,
+}
+
 impl Moodle {
-  pub fn new(base_url: &Url, username: &str, password: &str) -> Result<Moodle> {
+  /// Connect to `base_url`, reusing a cached token for `username` when one
+  /// exists and still validates, and falling back to a fresh login
+  /// otherwise. The (possibly new) token is written back to the cache
+  /// unless `use_cache` is false.
+  pub fn connect(
+    base_url: &Url,
+    username: &str,
+    password: &str,
+    use_cache: bool,
+    retry: RetryPolicy,
+  ) -> Result<Moodle> {
+    let client = Client::new();
+    let mut cache = if use_cache {
+      TokenCache::load()
+    } else {
+      TokenCache::default()
+    };
+
+    if let Some(token) = cache.get(base_url, username) {
+      let cached = Moodle {
+        url: base_url.join(WS_PATH)?,
+        token: token.to_owned(),
+        client: client.clone(),
+        retry,
+      };
+
+      if cached.validate_token().is_ok() {
+        return Ok(cached);
+      }
+    }
+
+    let moodle = Self::login(base_url, username, password, client, retry)?;
+
+    if use_cache {
+      cache.set(base_url, username, moodle.token.clone());
+      cache.save()?;
+    }
+
+    Ok(moodle)
+  }
+
+  /// Remove any cached token for `(base_url, username)`.
+  pub fn clear_token(base_url: &Url, username: &str) -> Result<()> {
+    let mut cache = TokenCache::load();
+    cache.remove(base_url, username);
+    cache.save()
+  }
+
+  fn login(
+    base_url: &Url,
+    username: &str,
+    password: &str,
+    client: Client,
+    retry: RetryPolicy,
+  ) -> Result<Moodle> {
     let login = serde_json::from_reader::<_, LoginResponse>(
-      Client::new()
+      client
         .post(base_url.join(LOGIN_PATH)?)
         .form(&HashMap::from([
           ("username", username),
@@ -69,22 +150,57 @@ impl Moodle {
       Ok(Moodle {
         url: base_url.join(WS_PATH)?,
         token,
+        client,
+        retry,
       })
     } else {
       Err(Error::Login(login.error))
     }
   }
 
+  /// Cheap call used to check whether a cached token is still accepted by
+  /// the server.
+  fn validate_token(&self) -> Result<()> {
+    self
+      .post::<SiteInfo>(HashMap::from([(
+        "wsfunction",
+        "core_webservice_get_site_info",
+      )]))
+      .map(|_| ())
+  }
+
   fn post<T>(&self, params: HashMap<&str, &str>) -> Result<T>
   where
     T: de::DeserializeOwned,
   {
-    let mut params = params;
+    self.retry.run(|| self.post_once(&params))
+  }
+
+  fn post_once<T>(&self, params: &HashMap<&str, &str>) -> Result<T>
+  where
+    T: de::DeserializeOwned,
+  {
+    let mut params = params.clone();
     params.insert("wstoken", &self.token);
 
-    Ok(serde_json::from_reader::<_, T>(
-      Client::new().post(self.url.clone()).form(&params).send()?,
-    )?)
+    let response = self.client.post(self.url.clone()).form(&params).send()?;
+    let status = response.status();
+    if !status.is_success() {
+      return Err(Error::Status { status });
+    }
+
+    let body = response.text()?;
+
+    match serde_json::from_str::<T>(&body) {
+      Ok(value) => Ok(value),
+      Err(err) => match serde_json::from_str::<MoodleException>(&body) {
+        Ok(exception) => Err(Error::Exception {
+          errorcode: exception.errorcode,
+          message: exception.message,
+        }),
+        Err(_) => Err(err.into()),
+      },
+    }
   }
 
   pub fn upload_grade(
@@ -116,19 +232,88 @@ impl Moodle {
     self.post(params)
   }
 
+  /// Download `file` to `path`, retrying transient failures. A retry
+  /// resumes from the bytes already written rather than starting over, by
+  /// sending a `Range` header for the remainder of the file.
   pub fn download_file(
     &self,
     file: &SubmissionFile,
     path: impl AsRef<Path>,
   ) -> Result<()> {
-    Client::new()
+    let path = path.as_ref();
+    self.retry.run(|| self.download_file_once(file, path))
+  }
+
+  fn download_file_once(
+    &self,
+    file: &SubmissionFile,
+    path: &Path,
+  ) -> Result<()> {
+    let written = fs::metadata(path).map_or(0, |metadata| metadata.len());
+
+    let mut request = self
+      .client
       .post(file.fileurl.as_str())
-      .form(&HashMap::from([("token", &self.token)]))
-      .send()?
-      .copy_to(&mut File::create(path)?)?;
+      .form(&HashMap::from([("token", &self.token)]));
+
+    if written > 0 {
+      request = request.header(RANGE, format!("bytes={written}-"));
+    }
+
+    let mut response = request.send()?;
+    let status = response.status();
+    if !status.is_success() {
+      return Err(Error::Status { status });
+    }
+
+    let resumed = written > 0 && status == StatusCode::PARTIAL_CONTENT;
+
+    let mut out = File::options()
+      .create(true)
+      .write(true)
+      .append(resumed)
+      .truncate(!resumed)
+      .open(path)?;
+
+    response.copy_to(&mut out)?;
 
     Ok(())
   }
+
+  /// Download `files` concurrently using a bounded pool of `jobs` worker
+  /// threads, each reusing the shared client. Results are returned in the
+  /// same order as `files`; a failure on one file does not stop the rest.
+  pub fn download_submissions(
+    &self,
+    files: &[(SubmissionFile, PathBuf)],
+    jobs: usize,
+  ) -> Vec<Result<()>> {
+    let jobs = jobs.max(1).min(files.len().max(1));
+    let next = AtomicUsize::new(0);
+    let results =
+      Mutex::new((0..files.len()).map(|_| None).collect::<Vec<_>>());
+
+    std::thread::scope(|scope| {
+      for _ in 0..jobs {
+        scope.spawn(|| loop {
+          let idx = next.fetch_add(1, Ordering::SeqCst);
+          let Some((file, path)) = files.get(idx) else {
+            break;
+          };
+
+          let result = self.download_file(file, path);
+          results.lock().unwrap()[idx] = Some(result);
+        });
+      }
+    });
+
+    results
+      .into_inner()
+      .unwrap()
+      .into_iter()
+      .map(Option::unwrap)
+      .collect()
+  }
 }
 
 #[derive(Deserialize)]
@@ -234,7 +419,7 @@ pub struct MSubmission {
   pub userid: u64,
 
   #[serde(with = "ts_seconds")]
-  timemodified: DateTime<Utc>,
+  pub timemodified: DateTime<Utc>,
 
   #[serde(deserialize_with = "deserialize_files", rename = "plugins")]
   pub files: Vec<SubmissionFile>,
@@ -272,7 +457,7 @@ struct SubmissionFileArea {
   files: Option<Vec<SubmissionFile>>,
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Deserialize)]
 pub struct SubmissionFile {
   filename: String,
   fileurl: Url,
@@ -329,9 +514,9 @@ struct UserResponse(Vec<MUser>);
 
 #[derive(Deserialize, Serialize)]
 pub struct MUser {
-  id: u64,
-  fullname: String,
-  email: String,
+  pub id: u64,
+  pub fullname: String,
+  pub email: String,
 }
 
 impl Moodle {
@@ -352,3 +537,129 @@ impl Moodle {
     Err(Error::NotFound(NotFound::User, user_id))
   }
 }
+
+#[derive(Deserialize)]
+struct GradesResponse {
+  assignments: Vec<AssignmentGrades>,
+}
+
+#[derive(Deserialize)]
+struct AssignmentGrades {
+  assignmentid: u64,
+  grades: Vec<MGrade>,
+}
+
+#[derive(Deserialize)]
+struct MGrade {
+  userid: u64,
+
+  #[serde(deserialize_with = "deserialize_grade")]
+  grade: f32,
+}
+
+fn deserialize_grade<'de, D>(deserializer: D) -> result::Result<f32, D::Error>
+where
+  D: Deserializer<'de>,
+{
+  String::deserialize(deserializer)?
+    .parse()
+    .map_err(de::Error::custom)
+}
+
+impl Moodle {
+  /// Current grades for every user on `assignment_id`, keyed by user id.
+  /// A user missing from the map has not been graded yet.
+  pub fn get_grades(&self, assignment_id: u64) -> Result<HashMap<u64, f32>> {
+    let assignment_id_str = assignment_id.to_string();
+    let params = HashMap::from([
+      ("wsfunction", "mod_assign_get_grades"),
+      ("assignmentids[]", &assignment_id_str),
+    ]);
+
+    for assignment_grades in self.post::<GradesResponse>(params)?.assignments
+    {
+      if assignment_grades.assignmentid == assignment_id {
+        return Ok(
+          assignment_grades
+            .grades
+            .into_iter()
+            .map(|grade| (grade.userid, grade.grade))
+            .collect(),
+        );
+      }
+    }
+
+    Ok(HashMap::new())
+  }
+}
+
+#[derive(Deserialize)]
+struct SubmissionStatusResponse {
+  feedback: Option<SubmissionFeedback>,
+}
+
+#[derive(Deserialize)]
+struct SubmissionFeedback {
+  #[serde(deserialize_with = "deserialize_feedback_text", rename = "plugins")]
+  text: String,
+}
+
+fn deserialize_feedback_text<'de, D>(
+  deserializer: D,
+) -> result::Result<String, D::Error>
+where
+  D: Deserializer<'de>,
+{
+  for plugin in Vec::<FeedbackPlugin>::deserialize(deserializer)? {
+    if plugin.plugin_type == "comments" {
+      if let Some(field) = plugin
+        .editorfields
+        .unwrap_or_default()
+        .into_iter()
+        .find(|field| field.name == "comments")
+      {
+        return Ok(field.text);
+      }
+    }
+  }
+
+  Ok(String::new())
+}
+
+#[derive(Deserialize)]
+struct FeedbackPlugin {
+  #[serde(rename = "type")]
+  plugin_type: String,
+  editorfields: Option<Vec<EditorField>>,
+}
+
+#[derive(Deserialize)]
+struct EditorField {
+  name: String,
+  text: String,
+}
+
+impl Moodle {
+  /// Current feedback-comments text for `user_id` on `assignment_id`, used
+  /// to detect whether an upload would change it.
+  pub fn get_feedback(
+    &self,
+    assignment_id: u64,
+    user_id: u64,
+  ) -> Result<String> {
+    let assignment_id_str = assignment_id.to_string();
+    let user_id_str = user_id.to_string();
+    let params = HashMap::from([
+      ("wsfunction", "mod_assign_get_submission_status"),
+      ("assignid", &assignment_id_str),
+      ("userid", &user_id_str),
+    ]);
+
+    Ok(
+      self
+        .post::<SubmissionStatusResponse>(params)?
+        .feedback
+        .map_or_else(String::new, |feedback| feedback.text),
+    )
+  }
+}
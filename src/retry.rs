@@ -0,0 +1,66 @@
+use std::{
+  thread,
+  time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::moodle::{Error, Result};
+
+/// Exponential backoff used for transient web-service errors: the delay
+/// doubles on every attempt (capped at `MAX_DELAY`) and a small amount of
+/// jitter is added so a batch of retrying requests doesn't all land on the
+/// server in lockstep.
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+  pub max_retries: u32,
+  pub base_delay: Duration,
+}
+
+impl RetryPolicy {
+  const MAX_DELAY: Duration = Duration::from_secs(30);
+  const MAX_JITTER_MILLIS: u64 = 100;
+
+  pub fn run<T>(&self, mut attempt: impl FnMut() -> Result<T>) -> Result<T> {
+    let mut tries = 0;
+
+    loop {
+      match attempt() {
+        Ok(value) => return Ok(value),
+        Err(err) if tries < self.max_retries && is_retryable(&err) => {
+          let delay = self
+            .base_delay
+            .saturating_mul(1u32 << tries.min(31))
+            .min(Self::MAX_DELAY);
+          thread::sleep(delay + jitter(Self::MAX_JITTER_MILLIS));
+          tries += 1;
+        }
+        Err(err) => return Err(err),
+      }
+    }
+  }
+}
+
+fn is_retryable(err: &Error) -> bool {
+  match err {
+    Error::Reqwest(err) => {
+      err.is_timeout()
+        || err.is_connect()
+        || err.status().is_some_and(|status| status.is_server_error())
+    }
+    Error::Exception { errorcode, .. } => {
+      matches!(
+        errorcode.as_str(),
+        "dmlreadexception" | "dmlwriteexception" | "ratelimitexceeded"
+      )
+    }
+    Error::Status { status } => status.is_server_error(),
+    _ => false,
+  }
+}
+
+fn jitter(max_millis: u64) -> Duration {
+  let nanos = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map_or(0, |d| d.subsec_nanos());
+
+  Duration::from_millis(u64::from(nanos) % max_millis.max(1))
+}
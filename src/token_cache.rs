@@ -0,0 +1,101 @@
+use std::{
+  collections::HashMap,
+  fs, io,
+  path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::moodle::Result;
+
+/// Cache of Moodle web-service tokens keyed by `(base_url, username)`,
+/// persisted as YAML under the user's config dir so the CLI doesn't have
+/// to re-authenticate (and resend the password) on every invocation.
+#[derive(Default, Deserialize, Serialize)]
+pub struct TokenCache(HashMap<String, String>);
+
+impl TokenCache {
+  fn path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("moody").join("tokens.yml"))
+  }
+
+  pub fn load() -> TokenCache {
+    Self::path()
+      .and_then(|path| fs::read_to_string(path).ok())
+      .and_then(|contents| serde_yaml::from_str(&contents).ok())
+      .unwrap_or_default()
+  }
+
+  pub fn get(&self, base_url: &Url, username: &str) -> Option<&str> {
+    self.0.get(&Self::key(base_url, username)).map(String::as_str)
+  }
+
+  pub fn set(&mut self, base_url: &Url, username: &str, token: String) {
+    self.0.insert(Self::key(base_url, username), token);
+  }
+
+  pub fn remove(&mut self, base_url: &Url, username: &str) {
+    self.0.remove(&Self::key(base_url, username));
+  }
+
+  /// Persist the cache to disk. The token alone grants full API access, so
+  /// both the `moody` config directory and the cache file are locked down
+  /// to the owner only.
+  pub fn save(&self) -> Result<()> {
+    let Some(path) = Self::path() else {
+      return Ok(());
+    };
+
+    if let Some(parent) = path.parent() {
+      fs::create_dir_all(parent)?;
+      restrict_dir(parent)?;
+    }
+
+    write_restricted(&path, &serde_yaml::to_string(self)?)?;
+
+    Ok(())
+  }
+
+  fn key(base_url: &Url, username: &str) -> String {
+    format!("{base_url}|{username}")
+  }
+}
+
+#[cfg(unix)]
+fn restrict_dir(dir: &Path) -> io::Result<()> {
+  use std::os::unix::fs::PermissionsExt;
+
+  fs::set_permissions(dir, fs::Permissions::from_mode(0o700))
+}
+
+#[cfg(not(unix))]
+fn restrict_dir(_dir: &Path) -> io::Result<()> {
+  Ok(())
+}
+
+#[cfg(unix)]
+fn write_restricted(path: &Path, contents: &str) -> io::Result<()> {
+  use std::{
+    io::Write,
+    os::unix::fs::{OpenOptionsExt, PermissionsExt},
+  };
+
+  let mut file = fs::OpenOptions::new()
+    .write(true)
+    .create(true)
+    .truncate(true)
+    .mode(0o600)
+    .open(path)?;
+
+  // `mode()` on `open()` only governs permissions when the file is newly
+  // created, so if `path` already existed (e.g. from an older build) it
+  // would otherwise keep its prior, possibly world-readable, permissions.
+  file.set_permissions(fs::Permissions::from_mode(0o600))?;
+  file.write_all(contents.as_bytes())
+}
+
+#[cfg(not(unix))]
+fn write_restricted(path: &Path, contents: &str) -> io::Result<()> {
+  fs::write(path, contents)
+}
@@ -0,0 +1,144 @@
+use std::{
+  collections::HashSet,
+  fs,
+  path::Path,
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+  },
+  thread,
+  time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::moodle::{MSubmission, Moodle, Result};
+
+#[derive(Deserialize, Serialize)]
+struct LogEntry {
+  userid: u64,
+  timemodified: DateTime<Utc>,
+  files: Vec<String>,
+}
+
+/// Poll `assignment_id` every `interval` for new or updated submissions,
+/// downloading each one as it appears into `output_dir` and recording it in
+/// `log_file`. Keeps polling across transient errors, giving up only after
+/// `max_failures` consecutive poll failures, and exits cleanly on Ctrl-C
+/// once any in-flight download has finished.
+pub fn run(
+  moodle: &Moodle,
+  assignment_id: u64,
+  output_dir: &Path,
+  log_file: &Path,
+  interval: Duration,
+  max_failures: u32,
+) -> Result<()> {
+  let running = Arc::new(AtomicBool::new(true));
+  {
+    let running = running.clone();
+    ctrlc::set_handler(move || running.store(false, Ordering::SeqCst))
+      .expect("failed to set Ctrl-C handler");
+  }
+
+  let mut log = load_log(log_file);
+  let mut seen: HashSet<(u64, DateTime<Utc>)> = log
+    .iter()
+    .map(|entry| (entry.userid, entry.timemodified))
+    .collect();
+
+  let mut consecutive_failures = 0;
+
+  while running.load(Ordering::SeqCst) {
+    match moodle.get_submissions(assignment_id) {
+      Ok(submissions) => {
+        consecutive_failures = 0;
+
+        for submission in submissions {
+          if !running.load(Ordering::SeqCst) {
+            break;
+          }
+
+          let key = (submission.userid, submission.timemodified);
+          if seen.contains(&key) {
+            continue;
+          }
+
+          match download_submission(moodle, &submission, output_dir) {
+            Ok(files) => {
+              seen.insert(key);
+              log.push(LogEntry {
+                userid: submission.userid,
+                timemodified: submission.timemodified,
+                files,
+              });
+              save_log(log_file, &log)?;
+            }
+            Err(err) => eprintln!(
+              "Failed to download submission for user {} :: {err}",
+              submission.userid
+            ),
+          }
+        }
+      }
+      Err(err) => {
+        consecutive_failures += 1;
+        eprintln!(
+          "Poll failed ({consecutive_failures}/{max_failures}) :: {err}"
+        );
+
+        if consecutive_failures >= max_failures {
+          return Err(err);
+        }
+      }
+    }
+
+    sleep_interruptible(&running, interval);
+  }
+
+  Ok(())
+}
+
+fn download_submission(
+  moodle: &Moodle,
+  submission: &MSubmission,
+  output_dir: &Path,
+) -> Result<Vec<String>> {
+  let user_dir = output_dir.join(submission.userid.to_string());
+  fs::create_dir_all(&user_dir)?;
+
+  let mut files = Vec::with_capacity(submission.files.len());
+  for file in &submission.files {
+    let path = user_dir.join(file.fullpath());
+    if let Some(parent) = path.parent() {
+      fs::create_dir_all(parent)?;
+    }
+
+    moodle.download_file(file, &path)?;
+    files.push(path.display().to_string());
+  }
+
+  Ok(files)
+}
+
+fn sleep_interruptible(running: &AtomicBool, interval: Duration) {
+  let step = Duration::from_secs(1).min(interval);
+  let mut elapsed = Duration::ZERO;
+
+  while elapsed < interval && running.load(Ordering::SeqCst) {
+    thread::sleep(step);
+    elapsed += step;
+  }
+}
+
+fn load_log(path: &Path) -> Vec<LogEntry> {
+  fs::read_to_string(path)
+    .ok()
+    .and_then(|contents| serde_yaml::from_str(&contents).ok())
+    .unwrap_or_default()
+}
+
+fn save_log(path: &Path, log: &[LogEntry]) -> Result<()> {
+  Ok(fs::write(path, serde_yaml::to_string(log)?)?)
+}